@@ -0,0 +1,574 @@
+//! Diffing of two decoded metadata values.
+//!
+//! Runtime upgrades happen frequently, and tooling (CLIs, release-review scripts, dashboards)
+//! regularly needs to know what changed between the metadata of two runtimes, for example the
+//! ones found in two consecutive blocks. [`diff`] compares two [`DecodedMetadata`] values and
+//! produces a [`MetadataDiff`], a serializable tree of what was added, removed, or changed,
+//! rather than a human-oriented text dump.
+
+use super::decode::DecodedMetadata;
+
+use alloc::{
+    borrow::ToOwned as _,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+
+/// Difference between two runtimes' metadata, as produced by [`diff`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct MetadataDiff {
+    /// Names of the pallets present in the new metadata but not in the old one.
+    pub pallets_added: Vec<String>,
+    /// Names of the pallets present in the old metadata but not in the new one.
+    pub pallets_removed: Vec<String>,
+    /// Pallets present in both metadata but whose storage, calls, events, constants, or errors
+    /// differ.
+    pub pallets_changed: Vec<PalletDiff>,
+}
+
+/// Difference between the two versions of a single pallet.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PalletDiff {
+    /// Name of the pallet.
+    pub name: String,
+    /// Differences in the pallet's storage entries, keyed by entry name.
+    pub storage: Vec<ItemDiff>,
+    /// Differences in the pallet's calls, keyed by call name.
+    pub calls: Vec<ItemDiff>,
+    /// Differences in the pallet's events, keyed by event name.
+    pub events: Vec<ItemDiff>,
+    /// Differences in the pallet's constants, keyed by constant name.
+    pub constants: Vec<ItemDiff>,
+    /// Differences in the pallet's errors, keyed by error name.
+    pub errors: Vec<ItemDiff>,
+}
+
+impl PalletDiff {
+    /// Returns `true` if none of the categories contain any difference.
+    fn is_empty(&self) -> bool {
+        self.storage.is_empty()
+            && self.calls.is_empty()
+            && self.events.is_empty()
+            && self.constants.is_empty()
+            && self.errors.is_empty()
+    }
+}
+
+/// Difference concerning a single named item (a storage entry, a call, an event, a constant, or
+/// an error) within a pallet.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ItemDiff {
+    /// Name of the item.
+    pub name: String,
+    /// What changed about the item.
+    pub change: ItemChange,
+}
+
+/// Kind of change that happened to an [`ItemDiff`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum ItemChange {
+    /// The item is present in the new metadata but not in the old one.
+    Added {
+        /// Human-readable description of the item's type in the new metadata.
+        ty: String,
+    },
+    /// The item is present in the old metadata but not in the new one.
+    Removed {
+        /// Human-readable description of the item's type in the old metadata.
+        ty: String,
+    },
+    /// The item is present in both, but its type changed.
+    Changed {
+        /// Human-readable description of the item's type in the old metadata.
+        old_ty: String,
+        /// Human-readable description of the item's type in the new metadata.
+        new_ty: String,
+    },
+}
+
+/// Compares the metadata of two runtimes and returns a structured description of what changed.
+///
+/// `old` and `new` are typically obtained by decoding the metadata of two consecutive blocks
+/// with [`decode_metadata`](super::decode::decode_metadata).
+pub fn diff(old: &DecodedMetadata, new: &DecodedMetadata) -> MetadataDiff {
+    let old_pallets = pallet_snapshots(old);
+    let new_pallets = pallet_snapshots(new);
+
+    let old_names: BTreeSet<&String> = old_pallets.keys().collect();
+    let new_names: BTreeSet<&String> = new_pallets.keys().collect();
+
+    let pallets_added = new_names
+        .difference(&old_names)
+        .map(|name| (*name).clone())
+        .collect();
+    let pallets_removed = old_names
+        .difference(&new_names)
+        .map(|name| (*name).clone())
+        .collect();
+
+    let pallets_changed = old_names
+        .intersection(&new_names)
+        .filter_map(|name| {
+            let diff = diff_pallet(name, &old_pallets[*name], &new_pallets[*name]);
+            if diff.is_empty() {
+                None
+            } else {
+                Some(diff)
+            }
+        })
+        .collect();
+
+    MetadataDiff {
+        pallets_added,
+        pallets_removed,
+        pallets_changed,
+    }
+}
+
+fn diff_pallet(name: &str, old: &PalletSnapshot, new: &PalletSnapshot) -> PalletDiff {
+    PalletDiff {
+        name: name.into(),
+        storage: diff_items(&old.storage, &new.storage),
+        calls: diff_items(&old.calls, &new.calls),
+        events: diff_items(&old.events, &new.events),
+        constants: diff_items(&old.constants, &new.constants),
+        errors: diff_items(&old.errors, &new.errors),
+    }
+}
+
+fn diff_items(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> Vec<ItemDiff> {
+    let old_names: BTreeSet<&String> = old.keys().collect();
+    let new_names: BTreeSet<&String> = new.keys().collect();
+
+    let mut out = Vec::new();
+
+    for name in new_names.difference(&old_names) {
+        out.push(ItemDiff {
+            name: (*name).clone(),
+            change: ItemChange::Added {
+                ty: new[*name].clone(),
+            },
+        });
+    }
+
+    for name in old_names.difference(&new_names) {
+        out.push(ItemDiff {
+            name: (*name).clone(),
+            change: ItemChange::Removed {
+                ty: old[*name].clone(),
+            },
+        });
+    }
+
+    for name in old_names.intersection(&new_names) {
+        let old_ty = &old[*name];
+        let new_ty = &new[*name];
+        if old_ty != new_ty {
+            out.push(ItemDiff {
+                name: (*name).clone(),
+                change: ItemChange::Changed {
+                    old_ty: old_ty.clone(),
+                    new_ty: new_ty.clone(),
+                },
+            });
+        }
+    }
+
+    out
+}
+
+/// Flattened, version-agnostic view of a single pallet, used as the common ground to diff a
+/// `V14` pallet against a `V15` one (or against another of the same version).
+struct PalletSnapshot {
+    storage: BTreeMap<String, String>,
+    calls: BTreeMap<String, String>,
+    events: BTreeMap<String, String>,
+    constants: BTreeMap<String, String>,
+    errors: BTreeMap<String, String>,
+}
+
+fn pallet_snapshots(metadata: &DecodedMetadata) -> BTreeMap<String, PalletSnapshot> {
+    match metadata {
+        DecodedMetadata::V14(md) => md
+            .pallets
+            .iter()
+            .map(|pallet| (pallet.name.clone(), snapshot_v14(&md.types, pallet)))
+            .collect(),
+        DecodedMetadata::V15(md) => md
+            .pallets
+            .iter()
+            .map(|pallet| (pallet.name.clone(), snapshot_v15(&md.types, pallet)))
+            .collect(),
+    }
+}
+
+fn snapshot_v14(
+    registry: &scale_info::PortableRegistry,
+    pallet: &frame_metadata::v14::PalletMetadata<scale_info::form::PortableForm>,
+) -> PalletSnapshot {
+    PalletSnapshot {
+        storage: pallet
+            .storage
+            .iter()
+            .flat_map(|storage| storage.entries.iter())
+            .map(|entry| (entry.name.clone(), describe_storage_entry(registry, entry)))
+            .collect(),
+        calls: pallet
+            .calls
+            .as_ref()
+            .map(|calls| variants_as_map(registry, calls.ty))
+            .unwrap_or_default(),
+        events: pallet
+            .event
+            .as_ref()
+            .map(|event| variants_as_map(registry, event.ty))
+            .unwrap_or_default(),
+        constants: pallet
+            .constants
+            .iter()
+            .map(|constant| (constant.name.clone(), describe_type(registry, constant.ty)))
+            .collect(),
+        errors: pallet
+            .error
+            .as_ref()
+            .map(|error| variants_as_map(registry, error.ty))
+            .unwrap_or_default(),
+    }
+}
+
+fn snapshot_v15(
+    registry: &scale_info::PortableRegistry,
+    pallet: &frame_metadata::v15::PalletMetadata<scale_info::form::PortableForm>,
+) -> PalletSnapshot {
+    PalletSnapshot {
+        storage: pallet
+            .storage
+            .iter()
+            .flat_map(|storage| storage.entries.iter())
+            .map(|entry| (entry.name.clone(), describe_storage_entry_v15(registry, entry)))
+            .collect(),
+        calls: pallet
+            .calls
+            .as_ref()
+            .map(|calls| variants_as_map(registry, calls.ty))
+            .unwrap_or_default(),
+        events: pallet
+            .event
+            .as_ref()
+            .map(|event| variants_as_map(registry, event.ty))
+            .unwrap_or_default(),
+        constants: pallet
+            .constants
+            .iter()
+            .map(|constant| (constant.name.clone(), describe_type(registry, constant.ty)))
+            .collect(),
+        errors: pallet
+            .error
+            .as_ref()
+            .map(|error| variants_as_map(registry, error.ty))
+            .unwrap_or_default(),
+    }
+}
+
+/// Resolves every variant of the enum type `type_id` (a pallet's calls, events, or errors) into
+/// a map of variant name to a human-readable description of its fields.
+fn variants_as_map(registry: &scale_info::PortableRegistry, type_id: u32) -> BTreeMap<String, String> {
+    let Some(ty) = registry.resolve(type_id) else {
+        return BTreeMap::new();
+    };
+
+    let scale_info::TypeDef::Variant(variant) = &ty.type_def else {
+        return BTreeMap::new();
+    };
+
+    variant
+        .variants
+        .iter()
+        .map(|variant| {
+            let fields = variant
+                .fields
+                .iter()
+                .map(|field| describe_type(registry, field.ty))
+                .collect::<Vec<_>>()
+                .join(", ");
+            (variant.name.clone(), fields)
+        })
+        .collect()
+}
+
+fn describe_storage_entry(
+    registry: &scale_info::PortableRegistry,
+    entry: &frame_metadata::v14::StorageEntryMetadata<scale_info::form::PortableForm>,
+) -> String {
+    match &entry.ty {
+        frame_metadata::v14::StorageEntryType::Plain(ty) => describe_type(registry, *ty),
+        frame_metadata::v14::StorageEntryType::Map { hashers, key, value } => {
+            describe_map(registry, hashers, *key, *value)
+        }
+    }
+}
+
+fn describe_storage_entry_v15(
+    registry: &scale_info::PortableRegistry,
+    entry: &frame_metadata::v15::StorageEntryMetadata<scale_info::form::PortableForm>,
+) -> String {
+    match &entry.ty {
+        frame_metadata::v15::StorageEntryType::Plain(ty) => describe_type(registry, *ty),
+        frame_metadata::v15::StorageEntryType::Map { hashers, key, value } => {
+            describe_map(registry, hashers, *key, *value)
+        }
+    }
+}
+
+/// Describes a storage map, including its key type(s) and hasher(s), so that a runtime upgrade
+/// that changes either (a real, breaking change for storage-reading clients) is detected as a
+/// change even when the value type stays the same.
+fn describe_map(
+    registry: &scale_info::PortableRegistry,
+    hashers: &[frame_metadata::v14::StorageHasher],
+    key: u32,
+    value: u32,
+) -> String {
+    let hashers = hashers
+        .iter()
+        .map(|hasher| format!("{:?}", hasher))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "Map<{} [{}] -> {}>",
+        describe_type(registry, key),
+        hashers,
+        describe_type(registry, value)
+    )
+}
+
+/// Resolves `type_id` in `registry` into a short human-readable description, falling back to
+/// the raw numeric ID if the type can't be resolved (which shouldn't happen for well-formed
+/// metadata).
+///
+/// For a named (path-bearing) type, the generic type parameters are included alongside the
+/// path, so that e.g. `BoundedVec<Foo, Bar>` is distinguished from `BoundedVec<Foo, Baz>`. This
+/// is only a one-level approximation of the type's full shape: two types can still have the
+/// same description here while differing deeper in their field layout (for example, a change
+/// entirely internal to `Baz`'s own fields). Catching that would require comparing the full
+/// type graphs across the two registries, which [`diff`] doesn't currently do.
+///
+/// For a path-less type (sequences, arrays, tuples, compact-encoded types, and the like), the
+/// description is built structurally from the type's definition (e.g. `Vec<u8>`, `(A, B)`)
+/// rather than from its registry-local type ID, since that ID has no meaning across the two
+/// registries being compared and would otherwise produce spurious differences.
+fn describe_type(registry: &scale_info::PortableRegistry, type_id: u32) -> String {
+    let Some(ty) = registry.resolve(type_id) else {
+        return format!("#{}", type_id);
+    };
+
+    if ty.path.segments.is_empty() {
+        return describe_type_def(registry, &ty.type_def);
+    }
+
+    let path = ty.path.segments.join("::");
+    if ty.type_params.is_empty() {
+        return path;
+    }
+
+    let params = ty
+        .type_params
+        .iter()
+        .map(|param| match param.ty {
+            Some(ty) => describe_type(registry, ty),
+            None => param.name.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{}<{}>", path, params)
+}
+
+/// Structurally describes a path-less [`scale_info::TypeDef`], recursing into its element/member
+/// types rather than Debug-printing the registry-local type IDs they reference.
+fn describe_type_def(
+    registry: &scale_info::PortableRegistry,
+    type_def: &scale_info::TypeDef<scale_info::form::PortableForm>,
+) -> String {
+    match type_def {
+        scale_info::TypeDef::Primitive(primitive) => format!("{:?}", primitive),
+        scale_info::TypeDef::Sequence(seq) => {
+            format!("Vec<{}>", describe_type(registry, seq.type_param))
+        }
+        scale_info::TypeDef::Array(arr) => {
+            format!("[{}; {}]", describe_type(registry, arr.type_param), arr.len)
+        }
+        scale_info::TypeDef::Tuple(tuple) => {
+            let members = tuple
+                .fields
+                .iter()
+                .map(|field| describe_type(registry, *field))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("({})", members)
+        }
+        scale_info::TypeDef::Compact(compact) => {
+            format!("Compact<{}>", describe_type(registry, compact.type_param))
+        }
+        scale_info::TypeDef::BitSequence(bitseq) => format!(
+            "BitSequence<{}, {}>",
+            describe_type(registry, bitseq.bit_store_type),
+            describe_type(registry, bitseq.bit_order_type),
+        ),
+        // Composite and Variant types without a path are unusual (most real-world structs and
+        // enums carry a Rust path), but describe them structurally rather than falling back to
+        // Debug-printing type IDs, for the same reason as every other case here.
+        scale_info::TypeDef::Composite(composite) => {
+            let fields = composite
+                .fields
+                .iter()
+                .map(|field| describe_type(registry, field.ty))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{{{}}}", fields)
+        }
+        scale_info::TypeDef::Variant(variant) => {
+            let variants = variant
+                .variants
+                .iter()
+                .map(|variant| variant.name.clone())
+                .collect::<Vec<_>>()
+                .join(" | ");
+            format!("enum {{{}}}", variants)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a [`scale_info::PortableRegistry`] out of `types`, registered in order. Since
+    /// these tests only register primitive types (no nested substructure to register first),
+    /// the resulting portable type IDs are simply `0, 1, 2, ...` in registration order, which
+    /// the tests rely on instead of searching the registry back for a given `MetaType`.
+    fn registry_with(types: &[scale_info::MetaType]) -> scale_info::PortableRegistry {
+        let mut registry = scale_info::Registry::new();
+        for ty in types {
+            registry.register_type(ty);
+        }
+        registry.into()
+    }
+
+    fn pallet_with_storage(
+        name: &str,
+        entry_name: &str,
+        value_ty: u32,
+    ) -> frame_metadata::v14::PalletMetadata<scale_info::form::PortableForm> {
+        frame_metadata::v14::PalletMetadata {
+            name: name.to_owned(),
+            storage: Some(frame_metadata::v14::PalletStorageMetadata {
+                prefix: name.to_owned(),
+                entries: vec![frame_metadata::v14::StorageEntryMetadata {
+                    name: entry_name.to_owned(),
+                    modifier: frame_metadata::v14::StorageEntryModifier::Default,
+                    ty: frame_metadata::v14::StorageEntryType::Plain(value_ty),
+                    default: Vec::new(),
+                    docs: Vec::new(),
+                }],
+            }),
+            calls: None,
+            event: None,
+            constants: Vec::new(),
+            error: None,
+            index: 0,
+        }
+    }
+
+    fn decoded_v14(
+        registry: scale_info::PortableRegistry,
+        pallets: Vec<frame_metadata::v14::PalletMetadata<scale_info::form::PortableForm>>,
+    ) -> DecodedMetadata {
+        DecodedMetadata::V14(frame_metadata::v14::RuntimeMetadataV14 {
+            types: registry,
+            pallets,
+            extrinsic: frame_metadata::v14::ExtrinsicMetadata {
+                ty: 0,
+                version: 4,
+                signed_extensions: Vec::new(),
+            },
+            ty: 0,
+        })
+    }
+
+    #[test]
+    fn detects_added_removed_and_changed_pallets() {
+        // Registration order: id 0 is `u64`.
+        let old_registry = registry_with(&[scale_info::MetaType::new::<u64>()]);
+        let old = decoded_v14(
+            old_registry,
+            vec![
+                pallet_with_storage("PalletA", "Value", 0),
+                pallet_with_storage("PalletB", "Value", 0),
+            ],
+        );
+
+        // Registration order: id 0 is `u32`, id 1 is `u8`.
+        let new_registry = registry_with(&[
+            scale_info::MetaType::new::<u32>(),
+            scale_info::MetaType::new::<u8>(),
+        ]);
+        let new = decoded_v14(
+            new_registry,
+            vec![
+                pallet_with_storage("PalletA", "Value", 0),
+                pallet_with_storage("PalletC", "Value", 1),
+            ],
+        );
+
+        let result = diff(&old, &new);
+
+        assert_eq!(result.pallets_added, vec!["PalletC".to_owned()]);
+        assert_eq!(result.pallets_removed, vec!["PalletB".to_owned()]);
+        assert_eq!(result.pallets_changed.len(), 1);
+        assert_eq!(result.pallets_changed[0].name, "PalletA");
+        assert_eq!(
+            result.pallets_changed[0].storage,
+            vec![ItemDiff {
+                name: "Value".to_owned(),
+                change: ItemChange::Changed {
+                    old_ty: "U64".to_owned(),
+                    new_ty: "U32".to_owned(),
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn identical_metadata_from_different_registries_has_no_diff() {
+        // Regression test: the same logical pallet built from two distinct `PortableRegistry`s
+        // (so every type resolves to a different, registry-local numeric ID) must compare as
+        // unchanged. Before describing path-less types structurally, this produced spurious
+        // `Changed` entries for every non-path type, such as this plain `u8` value.
+        //
+        // Old registry: id 0 is `u8`. New registry: id 0 is `u32`, id 1 is `u8` — same logical
+        // type, different numeric ID.
+        let old_registry = registry_with(&[scale_info::MetaType::new::<u8>()]);
+        let old = decoded_v14(
+            old_registry,
+            vec![pallet_with_storage("PalletA", "Value", 0)],
+        );
+
+        let new_registry = registry_with(&[
+            scale_info::MetaType::new::<u32>(),
+            scale_info::MetaType::new::<u8>(),
+        ]);
+        let new = decoded_v14(
+            new_registry,
+            vec![pallet_with_storage("PalletA", "Value", 1)],
+        );
+
+        let result = diff(&old, &new);
+
+        assert!(result.pallets_added.is_empty());
+        assert!(result.pallets_removed.is_empty());
+        assert!(result.pallets_changed.is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+//! Structured decoding of the metadata into typed `frame-metadata` values.
+//!
+//! The functions in [`query`](super) only give access to the metadata as raw SCALE-encoded
+//! bytes, leaving all interpretation up to the caller. The functions in this module, gated
+//! behind the `decode` feature, go one step further and turn these bytes into the strongly
+//! typed structures defined by the `frame-metadata` crate, so that callers can look up storage
+//! items, calls, events and type information without re-implementing SCALE parsing themselves.
+
+use super::Error;
+
+use parity_scale_codec::Decode as _;
+
+/// Metadata that has successfully been decoded into one of the versions that this crate knows
+/// how to interpret.
+#[derive(Debug, Clone)]
+pub enum DecodedMetadata {
+    V14(frame_metadata::v14::RuntimeMetadataV14),
+    V15(frame_metadata::v15::RuntimeMetadataV15),
+}
+
+impl DecodedMetadata {
+    /// Returns the metadata format version that this value was decoded from.
+    pub fn version(&self) -> u32 {
+        match self {
+            DecodedMetadata::V14(_) => 14,
+            DecodedMetadata::V15(_) => 15,
+        }
+    }
+}
+
+/// Decodes SCALE-encoded metadata, as returned by for example
+/// [`metadata_from_virtual_machine_prototype`](super::metadata_from_virtual_machine_prototype),
+/// into a [`DecodedMetadata`].
+pub fn decode_metadata(scale_encoded_metadata: &[u8]) -> Result<DecodedMetadata, Error> {
+    let prefixed =
+        frame_metadata::RuntimeMetadataPrefixed::decode(&mut &scale_encoded_metadata[..])
+            .map_err(|_| Error::Decode)?;
+    unprefix(prefixed.1)
+}
+
+/// Strips the `RuntimeMetadataPrefixed` wrapper, yielding a [`DecodedMetadata`] for the
+/// versions that this crate supports, and an error for every other version.
+fn unprefix(metadata: frame_metadata::RuntimeMetadata) -> Result<DecodedMetadata, Error> {
+    match metadata {
+        frame_metadata::RuntimeMetadata::V0(_) => Err(Error::UnsupportedVersion(0)),
+        frame_metadata::RuntimeMetadata::V1(_) => Err(Error::UnsupportedVersion(1)),
+        frame_metadata::RuntimeMetadata::V2(_) => Err(Error::UnsupportedVersion(2)),
+        frame_metadata::RuntimeMetadata::V3(_) => Err(Error::UnsupportedVersion(3)),
+        frame_metadata::RuntimeMetadata::V4(_) => Err(Error::UnsupportedVersion(4)),
+        frame_metadata::RuntimeMetadata::V5(_) => Err(Error::UnsupportedVersion(5)),
+        frame_metadata::RuntimeMetadata::V6(_) => Err(Error::UnsupportedVersion(6)),
+        frame_metadata::RuntimeMetadata::V7(_) => Err(Error::UnsupportedVersion(7)),
+        frame_metadata::RuntimeMetadata::V8(_) => Err(Error::UnsupportedVersion(8)),
+        frame_metadata::RuntimeMetadata::V9(_) => Err(Error::UnsupportedVersion(9)),
+        frame_metadata::RuntimeMetadata::V10(_) => Err(Error::UnsupportedVersion(10)),
+        frame_metadata::RuntimeMetadata::V11(_) => Err(Error::UnsupportedVersion(11)),
+        frame_metadata::RuntimeMetadata::V12(_) => Err(Error::UnsupportedVersion(12)),
+        frame_metadata::RuntimeMetadata::V13(_) => Err(Error::UnsupportedVersion(13)),
+        frame_metadata::RuntimeMetadata::V14(md) => Ok(DecodedMetadata::V14(md)),
+        frame_metadata::RuntimeMetadata::V15(md) => Ok(DecodedMetadata::V15(md)),
+    }
+}
@@ -23,8 +23,13 @@
 
 use crate::executor;
 
-use core::convert::TryFrom as _;
-use parity_scale_codec::Decode as _;
+use core::{convert::TryFrom as _, iter};
+use parity_scale_codec::{Decode as _, Encode as _};
+
+#[cfg(feature = "decode")]
+pub mod decode;
+#[cfg(feature = "decode")]
+pub mod cmp;
 
 /// Retrieves the SCALE-encoded metadata from the runtime code of a block.
 ///
@@ -44,6 +49,28 @@ pub fn metadata_from_runtime_code(wasm_code: &[u8], heap_pages: u64) -> Result<V
 /// Returns back the same virtual machine prototype as was passed as parameter.
 pub fn metadata_from_virtual_machine_prototype(
     vm: executor::WasmVmPrototype,
+) -> Result<(Vec<u8>, executor::WasmVmPrototype), Error> {
+    metadata_from_virtual_machine_prototype_inner(vm, None)
+}
+
+/// Same as [`metadata_from_virtual_machine_prototype`], but rejects the metadata if its length
+/// prefix declares a size strictly larger than `max_len` bytes.
+///
+/// The metadata comes from executing untrusted Wasm code, and a malicious or buggy runtime can
+/// claim an arbitrarily large length, for example several gigabytes, in its length prefix. This
+/// function checks the declared length against `max_len` before copying any data out, so that
+/// such a runtime is rejected cleanly with [`Error::MetadataTooLarge`] instead of triggering a
+/// huge allocation.
+pub fn metadata_from_virtual_machine_prototype_with_limit(
+    vm: executor::WasmVmPrototype,
+    max_len: usize,
+) -> Result<(Vec<u8>, executor::WasmVmPrototype), Error> {
+    metadata_from_virtual_machine_prototype_inner(vm, Some(max_len))
+}
+
+fn metadata_from_virtual_machine_prototype_inner(
+    vm: executor::WasmVmPrototype,
+    max_len: Option<usize>,
 ) -> Result<(Vec<u8>, executor::WasmVmPrototype), Error> {
     let mut vm = vm
         .run_no_param("Metadata_metadata")
@@ -52,7 +79,40 @@ pub fn metadata_from_virtual_machine_prototype(
     let outcome = loop {
         match vm.state() {
             executor::State::ReadyToRun(r) => r.run(),
-            executor::State::Finished(data) => break remove_length_prefix(data)?,
+            executor::State::Finished(data) => break remove_length_prefix(data, max_len)?,
+            executor::State::Trapped => return Err(Error::Trapped),
+            executor::State::LogEmit { resolve, .. } => resolve.finish_call(()),
+
+            // Querying the metadata shouldn't require any extrinsic such as accessing the
+            // storage.
+            _ => return Err(Error::ExternalityNotAllowed),
+        }
+    };
+
+    Ok((outcome, vm.into_prototype()))
+}
+
+/// Retrieves the list of metadata versions supported by the runtime, using the `Metadata`
+/// runtime API v2.
+///
+/// Returns back the same virtual machine prototype as was passed as parameter.
+///
+/// > **Note**: Runtimes that don't implement the `Metadata` runtime API v2 don't expose this
+/// >           entry point, and calling this function on them will likely return a
+/// >           [`Error::VmInitialization`].
+pub fn metadata_versions_from_virtual_machine_prototype(
+    vm: executor::WasmVmPrototype,
+) -> Result<(Vec<u32>, executor::WasmVmPrototype), Error> {
+    let mut vm = vm
+        .run_no_param("Metadata_metadata_versions")
+        .map_err(Error::VmInitialization)?;
+
+    let outcome = loop {
+        match vm.state() {
+            executor::State::ReadyToRun(r) => r.run(),
+            executor::State::Finished(data) => {
+                break Vec::<u32>::decode(&mut &data[..]).map_err(|_| Error::VersionsDecode)?
+            }
             executor::State::Trapped => return Err(Error::Trapped),
             executor::State::LogEmit { resolve, .. } => resolve.finish_call(()),
 
@@ -65,6 +125,73 @@ pub fn metadata_from_virtual_machine_prototype(
     Ok((outcome, vm.into_prototype()))
 }
 
+/// Retrieves the SCALE-encoded metadata for a specific version from the given virtual machine
+/// prototype, using the `Metadata` runtime API v2.
+///
+/// Returns `None` if the runtime doesn't support the requested `version`, for example because
+/// it predates that version or has already dropped support for it. The list of versions
+/// currently supported by a runtime can be queried using
+/// [`metadata_versions_from_virtual_machine_prototype`].
+///
+/// Returns back the same virtual machine prototype as was passed as parameter.
+pub fn metadata_at_version_from_virtual_machine_prototype(
+    vm: executor::WasmVmPrototype,
+    version: u32,
+) -> Result<(Option<Vec<u8>>, executor::WasmVmPrototype), Error> {
+    metadata_at_version_from_virtual_machine_prototype_inner(vm, version, None)
+}
+
+/// Same as [`metadata_at_version_from_virtual_machine_prototype`], but rejects the metadata if
+/// its length prefix declares a size strictly larger than `max_len` bytes. See
+/// [`metadata_from_virtual_machine_prototype_with_limit`] for why this matters.
+pub fn metadata_at_version_from_virtual_machine_prototype_with_limit(
+    vm: executor::WasmVmPrototype,
+    version: u32,
+    max_len: usize,
+) -> Result<(Option<Vec<u8>>, executor::WasmVmPrototype), Error> {
+    metadata_at_version_from_virtual_machine_prototype_inner(vm, version, Some(max_len))
+}
+
+fn metadata_at_version_from_virtual_machine_prototype_inner(
+    vm: executor::WasmVmPrototype,
+    version: u32,
+    max_len: Option<usize>,
+) -> Result<(Option<Vec<u8>>, executor::WasmVmPrototype), Error> {
+    let mut vm = vm
+        .run_vectored("Metadata_metadata_at_version", iter::once(version.encode()))
+        .map_err(Error::VmInitialization)?;
+
+    let outcome = loop {
+        match vm.state() {
+            executor::State::ReadyToRun(r) => r.run(),
+            executor::State::Finished(data) => {
+                break parse_metadata_at_version_output(data, max_len)?
+            }
+            executor::State::Trapped => return Err(Error::Trapped),
+            executor::State::LogEmit { resolve, .. } => resolve.finish_call(()),
+
+            // Querying the metadata shouldn't require any extrinsic such as accessing the
+            // storage.
+            _ => return Err(Error::ExternalityNotAllowed),
+        }
+    };
+
+    Ok((outcome, vm.into_prototype()))
+}
+
+/// Parses the output of `Metadata_metadata_at_version`, namely a SCALE-encoded
+/// `Option<OpaqueMetadata>`.
+fn parse_metadata_at_version_output(
+    data: &[u8],
+    max_len: Option<usize>,
+) -> Result<Option<Vec<u8>>, Error> {
+    match data.split_first() {
+        Some((0, _)) => Ok(None),
+        Some((1, rest)) => Ok(Some(remove_length_prefix(rest, max_len)?)),
+        _ => Err(Error::BadOptionTag),
+    }
+}
+
 /// Error when retrieving the metadata.
 #[derive(Debug, derive_more::Display)]
 pub enum Error {
@@ -76,25 +203,58 @@ pub enum Error {
     ExternalityNotAllowed,
     /// Length prefix doesn't match actual length of the metadata.
     BadLengthPrefix,
+    /// The length prefix declares a metadata size larger than the configured maximum.
+    MetadataTooLarge,
+    /// Failed to decode the list of metadata versions returned by the runtime.
+    VersionsDecode,
+    /// The `Option` tag at the start of the `Metadata_metadata_at_version` output is neither
+    /// `0` nor `1`.
+    BadOptionTag,
+    /// Error while decoding the metadata into a [`decode::DecodedMetadata`].
+    #[cfg(feature = "decode")]
+    Decode,
+    /// The metadata is of a version that this crate doesn't know how to decode into a
+    /// structured representation.
+    #[cfg(feature = "decode")]
+    UnsupportedVersion(u32),
 }
 
 /// Removes the length prefix at the beginning of `metadata`. Returns an error if there is no
 /// valid length prefix.
-fn remove_length_prefix(metadata: &[u8]) -> Result<Vec<u8>, Error> {
+///
+/// If `max_len` is `Some`, also rejects metadata whose declared length is strictly larger than
+/// `max_len`, before the final `.to_owned()` copy. This guards against a malicious or buggy
+/// runtime claiming an unreasonably large length in order to make the host allocate wildly.
+fn remove_length_prefix(metadata: &[u8], max_len: Option<usize>) -> Result<Vec<u8>, Error> {
+    // Bail out early on an empty buffer. A non-empty but truncated buffer is rejected below,
+    // either by the `Compact` decoder or by the check of the length prefix against the
+    // buffer's actual length.
+    if metadata.is_empty() {
+        return Err(Error::BadLengthPrefix);
+    }
+
     // TODO: maybe don't use parity_scale_codec here
     // Decoded length prefix.
     let length = parity_scale_codec::Compact::<u64>::decode(&mut (&metadata[..]))
         .map_err(|_| Error::BadLengthPrefix)?;
 
+    let declared_len = usize::try_from(length.0).unwrap_or(usize::max_value());
+
+    if let Some(max_len) = max_len {
+        if declared_len > max_len {
+            return Err(Error::MetadataTooLarge);
+        }
+    }
+
     // Length of the decoded length prefix.
     let length_length =
         <parity_scale_codec::Compact<u64> as parity_scale_codec::CompactLen<u64>>::compact_len(
             &length.0,
         );
 
-    // Verify that the length prefix indeed matches the metadata's length.
-    if usize::try_from(length.0)
-        .unwrap_or(usize::max_value())
+    // Verify that the length prefix indeed matches the metadata's length. This also guarantees
+    // that the slicing below is in bounds.
+    if declared_len
         .checked_add(length_length)
         .ok_or(Error::BadLengthPrefix)?
         != metadata.len()